@@ -6,6 +6,25 @@ use legion::{
     world::{IntoComponentSource, TagLayout, TagSet},
 };
 
+/// Deletes `root` and every entity transitively parented to it, visiting each
+/// reachable entity at most once.
+fn despawn_subtree(world: &mut World, root: Entity) {
+    let mut to_despawn = vec![root];
+    let mut i = 0;
+    while i < to_despawn.len() {
+        let parent = to_despawn[i];
+        for (child, parent_component) in Read::<Parent>::query().iter_entities(world) {
+            if parent_component.0 == parent && !to_despawn.contains(&child) {
+                to_despawn.push(child);
+            }
+        }
+        i += 1;
+    }
+    for entity in to_despawn {
+        world.delete(entity);
+    }
+}
+
 pub trait WorldBuilderSource {
     fn build(&mut self) -> WorldBuilder;
 }
@@ -65,6 +84,22 @@ impl<'a> WorldBuilder<'a> {
         self
     }
 
+    /// Deletes `current_entity`, leaving anything parented to it alone.
+    pub fn despawn(&mut self) -> &mut Self {
+        if let Some(entity) = self.current_entity.take() {
+            self.world.delete(entity);
+        }
+        self
+    }
+
+    /// Deletes `current_entity` and every entity transitively parented to it.
+    pub fn despawn_recursive(&mut self) -> &mut Self {
+        if let Some(entity) = self.current_entity.take() {
+            despawn_subtree(self.world, entity);
+        }
+        self
+    }
+
     pub fn with_children(&mut self, mut build_children: impl FnMut(&mut Self) -> &mut Self) -> &mut Self {
         self.parent_entity = self.current_entity;
         self.current_entity = None;
@@ -112,6 +147,15 @@ pub struct CommandBufferBuilder<'a> {
 }
 
 impl<'a> CommandBufferBuilder<'a> {
+    /// The entity the next `with`/`exec` call will act on, if one has been
+    /// created or targeted. `insert` allocates and returns the entity's ID
+    /// synchronously, so this is available immediately, even though the
+    /// entity's components aren't written into the world until the command
+    /// buffer is flushed.
+    pub fn current_entity(&self) -> Option<Entity> {
+        self.current_entity
+    }
+
     pub fn entity(&mut self) -> &mut Self {
         let entity = *self.command_buffer.insert((), vec![()]).first().unwrap();
         self.current_entity = Some(entity);
@@ -151,6 +195,44 @@ impl<'a> CommandBufferBuilder<'a> {
         self
     }
 
+    /// Enqueues an arbitrary world mutation to run when the command buffer is
+    /// flushed, for operations the fluent API doesn't cover (conditional
+    /// component removal, relationship fix-ups, resource access, ...). The
+    /// closure receives the entity `current_entity` pointed at when `exec` was
+    /// called, if any, and keeps its place in the surrounding
+    /// `entity()`/`with()` chain.
+    pub fn exec(&mut self, f: impl FnOnce(&mut World, Option<Entity>) + 'static) -> &mut Self {
+        let current_entity = self.current_entity;
+        let mut f = Some(f);
+        self.command_buffer.exec_mut(move |world| {
+            if let Some(f) = f.take() {
+                f(world, current_entity);
+            }
+        });
+        self
+    }
+
+    /// Enqueues the deletion of `current_entity`, leaving anything parented to
+    /// it alone.
+    pub fn despawn(&mut self) -> &mut Self {
+        if let Some(entity) = self.current_entity.take() {
+            self.command_buffer.delete(entity);
+        }
+        self
+    }
+
+    /// Enqueues the deletion of `current_entity` and every entity transitively
+    /// parented to it. The hierarchy isn't known until the buffer runs, so the
+    /// child set is gathered from the world when this is flushed.
+    pub fn despawn_recursive(&mut self) -> &mut Self {
+        if let Some(entity) = self.current_entity.take() {
+            self.command_buffer.exec_mut(move |world| {
+                despawn_subtree(world, entity);
+            });
+        }
+        self
+    }
+
     pub fn with_children(&mut self, mut build_children: impl FnMut(&mut Self) -> &mut Self) -> &mut Self {
         let current_entity = self.current_entity.expect("Cannot add children without a parent. Try creating an entity first.");
         self.parent_entities.push(current_entity);
@@ -175,4 +257,178 @@ impl<'a> CommandBufferBuilder<'a> {
                 .add_component(current_entity, LocalTransform::identity());
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct A(u32);
+    #[derive(Copy, Clone, Debug, PartialEq)]
+    struct B(u32);
+
+    #[test]
+    fn world_builder_with_children_applies_components_to_the_right_entity() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+
+        world.build().entity().with(A(1)).with_children(|builder| {
+            builder.entity().with(B(2));
+            builder
+        });
+
+        let parent = <Read<A>>::query()
+            .iter_entities(&world)
+            .map(|(entity, _)| entity)
+            .next()
+            .unwrap();
+        let child = <Read<B>>::query()
+            .iter_entities(&world)
+            .map(|(entity, _)| entity)
+            .next()
+            .unwrap();
+
+        assert_ne!(parent, child);
+        assert!(world.get_component::<B>(parent).is_none());
+        assert!(world.get_component::<A>(child).is_none());
+    }
+
+    #[test]
+    fn command_buffer_builder_with_children_applies_components_to_the_right_entity() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut command_buffer = CommandBuffer::default();
+
+        command_buffer
+            .build()
+            .entity()
+            .with(A(1))
+            .with_children(|builder| {
+                builder.entity().with(B(2));
+                builder
+            });
+        command_buffer.write(&mut world);
+
+        let parent = <Read<A>>::query()
+            .iter_entities(&world)
+            .map(|(entity, _)| entity)
+            .next()
+            .unwrap();
+        let child = <Read<B>>::query()
+            .iter_entities(&world)
+            .map(|(entity, _)| entity)
+            .next()
+            .unwrap();
+
+        assert_ne!(parent, child);
+        assert!(world.get_component::<B>(parent).is_none());
+        assert!(world.get_component::<A>(child).is_none());
+    }
+
+    #[test]
+    fn despawn_recursive_removes_descendants() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut command_buffer = CommandBuffer::default();
+
+        command_buffer
+            .build()
+            .entity()
+            .with(A(1))
+            .with_children(|builder| {
+                builder.entity().with(B(2)).with_children(|builder| {
+                    builder.entity().with(B(3));
+                    builder
+                });
+                builder
+            });
+        command_buffer.write(&mut world);
+
+        let parent = <Read<A>>::query()
+            .iter_entities(&world)
+            .map(|(entity, _)| entity)
+            .next()
+            .unwrap();
+        assert_eq!(<Read<B>>::query().iter_entities(&world).count(), 2);
+
+        world.build().set_entity(parent).despawn_recursive();
+
+        assert!(!world.is_alive(parent));
+        assert_eq!(<Read<B>>::query().iter_entities(&world).count(), 0);
+    }
+
+    #[test]
+    fn despawning_a_child_does_not_clear_the_enclosing_parent_context() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut command_buffer = CommandBuffer::default();
+
+        command_buffer
+            .build()
+            .entity()
+            .with(A(1))
+            .with_children(|builder| {
+                builder.entity().with(B(2)).despawn();
+                builder.entity().with(B(3));
+                builder
+            });
+        command_buffer.write(&mut world);
+
+        let parent = <Read<A>>::query()
+            .iter_entities(&world)
+            .map(|(entity, _)| entity)
+            .next()
+            .unwrap();
+        let (surviving_child, _) = <Read<B>>::query()
+            .iter_entities(&world)
+            .find(|(_, b)| b.0 == 3)
+            .unwrap();
+
+        assert_eq!(<Read<B>>::query().iter_entities(&world).count(), 1);
+        assert_eq!(
+            world.get_component::<Parent>(surviving_child).map(|p| p.0),
+            Some(parent)
+        );
+    }
+
+    #[test]
+    fn exec_runs_against_the_flushed_world_and_sees_current_entity() {
+        let universe = Universe::new();
+        let mut world = universe.create_world();
+        let mut command_buffer = CommandBuffer::default();
+
+        command_buffer
+            .build()
+            .entity()
+            .with(A(1))
+            .exec(|world, entity| {
+                let entity = entity.unwrap();
+                assert!(world.get_component::<A>(entity).is_some());
+                let _ = world.add_component(entity, B(2));
+            });
+        command_buffer.write(&mut world);
+
+        let entity = <Read<A>>::query()
+            .iter_entities(&world)
+            .map(|(entity, _)| entity)
+            .next()
+            .unwrap();
+        assert_eq!(world.get_component::<B>(entity).as_deref(), Some(&B(2)));
+    }
+
+    #[test]
+    fn current_entity_is_available_before_the_command_buffer_is_flushed() {
+        let mut command_buffer = CommandBuffer::default();
+        let mut builder = command_buffer.build();
+
+        assert_eq!(builder.current_entity(), None);
+
+        builder.entity();
+        let entity = builder.current_entity();
+
+        assert!(entity.is_some());
+        builder.set_entity(entity.unwrap());
+        assert_eq!(builder.current_entity(), entity);
+    }
 }
\ No newline at end of file